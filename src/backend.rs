@@ -0,0 +1,113 @@
+//! Platform backend: resolves the ffmpeg/ffprobe binaries and hides the
+//! handful of Windows-only behaviors (console suppression, admin
+//! elevation) behind `#[cfg(windows)]` so the rest of the app doesn't need
+//! to know which OS it's running on.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[cfg(windows)]
+const FFMPEG_NAME: &str = "ffmpeg.exe";
+#[cfg(windows)]
+const FFPROBE_NAME: &str = "ffprobe.exe";
+#[cfg(not(windows))]
+const FFMPEG_NAME: &str = "ffmpeg";
+#[cfg(not(windows))]
+const FFPROBE_NAME: &str = "ffprobe";
+
+/// Resolves the ffmpeg binary: a copy bundled next to the exe wins,
+/// otherwise fall back to whatever `ffmpeg` is on PATH.
+pub fn ffmpeg_path(exe_dir: &Path) -> PathBuf {
+    resolve(exe_dir, FFMPEG_NAME)
+}
+
+/// Resolves the ffprobe binary the same way as [`ffmpeg_path`].
+pub fn ffprobe_path(exe_dir: &Path) -> PathBuf {
+    resolve(exe_dir, FFPROBE_NAME)
+}
+
+fn resolve(exe_dir: &Path, name: &str) -> PathBuf {
+    let bundled = exe_dir.join(name);
+    if bundled.is_file() {
+        return bundled;
+    }
+    // Bare name: `Command` resolves this against PATH itself.
+    PathBuf::from(name)
+}
+
+/// Suppresses the console window a child ffmpeg/ffprobe process would
+/// otherwise flash open on Windows. No-op elsewhere, where there's no
+/// console to hide.
+pub fn hide_console(cmd: &mut Command) -> &mut Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        cmd.creation_flags(0x0800_0000);
+    }
+    cmd
+}
+
+/// Checks if the current process has elevated (administrator)
+/// privileges. Windows-only: there's no equivalent concept here for
+/// Unix, where the app doesn't need elevation to run ffmpeg.
+#[cfg(windows)]
+pub fn is_elevated() -> bool {
+    use std::ptr::null_mut;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
+    use winapi::um::securitybaseapi::GetTokenInformation;
+    use winapi::um::winnt::{TokenElevation, HANDLE, TOKEN_ELEVATION, TOKEN_QUERY};
+
+    unsafe {
+        let mut token: HANDLE = null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return false;
+        }
+
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut size = std::mem::size_of::<TOKEN_ELEVATION>() as u32;
+
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut _ as *mut _,
+            size,
+            &mut size,
+        );
+
+        CloseHandle(token);
+        result != 0 && elevation.TokenIsElevated != 0
+    }
+}
+
+/// Relaunches the current exe elevated via `ShellExecuteW("runas", ...)`
+/// and exits this instance. Windows-only.
+#[cfg(windows)]
+pub fn relaunch_as_admin() {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr::null_mut;
+    use winapi::um::shellapi::ShellExecuteW;
+    use winapi::um::winuser::SW_SHOW;
+
+    let exe = std::env::current_exe().unwrap();
+    let widestring = |s: &str| -> Vec<u16> {
+        OsStr::new(s)
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect()
+    };
+    let exe_w = widestring(exe.to_str().unwrap());
+
+    unsafe {
+        ShellExecuteW(
+            null_mut(),
+            widestring("runas").as_ptr(),
+            exe_w.as_ptr(),
+            null_mut(),
+            null_mut(),
+            SW_SHOW,
+        );
+    }
+    std::process::exit(0);
+}
@@ -0,0 +1,170 @@
+//! HLS VOD packaging: segment the merged video and each detected audio
+//! rendition separately, then write a master playlist tying them
+//! together. Uses the `hls_m3u8` crate for the typed playlist builders
+//! (additional dependency: hls_m3u8 = "0.4").
+//!
+//! The external audio file can carry several language tracks (see
+//! `get_all_audio_tracks`), so unlike the single-file `.mkv` merge we
+//! expose every one of them as its own `EXT-X-MEDIA` audio rendition
+//! rather than baking in just the one the user picked.
+
+use hls_m3u8::tags::{ExtXMedia, ExtXStreamInf, MediaType};
+use hls_m3u8::types::StreamData;
+use hls_m3u8::MasterPlaylist;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{backend, AudioStream};
+
+/// One language rendition surfaced in the master playlist.
+pub struct AudioRendition {
+    pub language: String,
+    pub playlist_name: String,
+    pub is_default: bool,
+}
+
+/// Segments `video` (video-only, no audio) into an fMP4 HLS VOD variant
+/// under `out_dir`, returning the media playlist's file name.
+pub fn segment_video(video: &str, out_dir: &Path, exe_dir: &PathBuf) -> Result<String, String> {
+    let playlist_name = "video.m3u8";
+    run_hls_segment(
+        exe_dir,
+        &["-i", video, "-map", "0:v:0", "-an", "-c:v", "copy"],
+        out_dir,
+        "video_%03d.m4s",
+        "init_video.mp4",
+        playlist_name,
+    )?;
+    Ok(playlist_name.to_string())
+}
+
+/// Segments one audio stream from `audio` into its own HLS media
+/// playlist, named after the stream's index and language tag so the
+/// master playlist can reference it unambiguously.
+pub fn segment_audio(
+    audio: &str,
+    stream: &AudioStream,
+    out_dir: &Path,
+    exe_dir: &PathBuf,
+) -> Result<AudioRendition, String> {
+    let (index, language) = stream;
+    let playlist_name = format!("audio_{}_{}.m3u8", index, language);
+    run_hls_segment(
+        exe_dir,
+        &["-i", audio, "-map", &format!("0:{}", index), "-c:a", "copy"],
+        out_dir,
+        &format!("audio_{}_{}_%03d.m4s", index, language),
+        &format!("init_audio_{}_{}.mp4", index, language),
+        &playlist_name,
+    )?;
+    Ok(AudioRendition {
+        language: language.clone(),
+        playlist_name,
+        is_default: language.eq_ignore_ascii_case("por"),
+    })
+}
+
+fn run_hls_segment(
+    exe_dir: &PathBuf,
+    input_args: &[&str],
+    out_dir: &Path,
+    segment_pattern: &str,
+    init_filename: &str,
+    playlist_name: &str,
+) -> Result<(), String> {
+    let segment_path = out_dir.join(segment_pattern);
+    let init_path = out_dir.join(init_filename);
+    let playlist_path = out_dir.join(playlist_name);
+    let mut cmd = Command::new(backend::ffmpeg_path(exe_dir));
+    backend::hide_console(&mut cmd)
+        .args(&["-hide_banner", "-loglevel", "error", "-y"])
+        .args(input_args)
+        .args(&[
+            "-f",
+            "hls",
+            "-hls_time",
+            "6",
+            "-hls_playlist_type",
+            "vod",
+            // Segments are named `.m4s` (fMP4), so the muxer needs to be
+            // told to actually produce fMP4 payloads — its default is
+            // MPEG-TS, which would leave the container and the file
+            // extension disagreeing with each other.
+            "-hls_segment_type",
+            "fmp4",
+            "-hls_fmp4_init_filename",
+        ])
+        .arg(&init_path)
+        .arg("-hls_segment_filename")
+        .arg(segment_path)
+        .arg(playlist_path);
+    match cmd.status() {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => Err(format!("ffmpeg exit code {:?}", s.code())),
+        Err(e) => Err(format!("failed to run ffmpeg: {}", e)),
+    }
+}
+
+/// Strips characters that can't appear inside an HLS quoted-string
+/// attribute value (`"` and control characters). Language tags and file
+/// names end up there (`NAME="..."`, `LANGUAGE="..."`, `URI="..."`) and
+/// come from muxer-supplied metadata we don't control, not from anything
+/// the user typed — an untrusted `"` or stray control byte in there must
+/// not reach the playlist text verbatim.
+fn sanitize_attr_value(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| *c != '"' && !c.is_control())
+        .collect()
+}
+
+/// Builds the version-7 master playlist: one video variant plus one
+/// `AlternativeMedia` audio entry per rendition, with the auto-selected
+/// ("por", or the first track) marked `DEFAULT`. Returns `Err` instead of
+/// panicking on a rendition the playlist builder rejects (e.g. a language
+/// tag that's empty after sanitizing), so the caller can log and abort
+/// the merge cleanly rather than taking down the worker thread.
+pub fn build_master_playlist(
+    video_playlist: &str,
+    renditions: &[AudioRendition],
+) -> Result<String, String> {
+    const AUDIO_GROUP: &str = "aud";
+
+    let mut builder = MasterPlaylist::builder();
+    builder.version(7);
+
+    let default_idx = renditions.iter().position(|r| r.is_default).unwrap_or(0);
+    for (i, r) in renditions.iter().enumerate() {
+        let is_default = i == default_idx;
+        let language = sanitize_attr_value(&r.language);
+        let uri = sanitize_attr_value(&r.playlist_name);
+        let media = ExtXMedia::builder()
+            .media_type(MediaType::Audio)
+            .group_id(AUDIO_GROUP)
+            .name(language.clone())
+            .language(language)
+            .autoselect(true)
+            .is_default(is_default)
+            .uri(uri)
+            .build()
+            .map_err(|e| {
+                format!(
+                    "invalid EXT-X-MEDIA audio rendition '{}': {:?}",
+                    r.language, e
+                )
+            })?;
+        builder.alternative(media);
+    }
+
+    let stream_inf = ExtXStreamInf::builder()
+        .stream_data(StreamData::builder().bandwidth(5_000_000).build().unwrap())
+        .audio(AUDIO_GROUP)
+        .build()
+        .map_err(|e| format!("invalid EXT-X-STREAM-INF variant: {:?}", e))?;
+    builder.variant_stream(sanitize_attr_value(video_playlist), stream_inf);
+
+    builder
+        .build()
+        .map_err(|e| format!("invalid master playlist: {:?}", e))
+        .map(|p| p.to_string())
+}
@@ -0,0 +1,248 @@
+//! Batch merge queue: runs several (video, external-audio, output) jobs
+//! through ffmpeg concurrently, capped at a small worker pool. Muxing with
+//! `-c copy` is I/O-bound rather than CPU-bound, so we don't want one
+//! thread per core the way a transcode farm (e.g. Av1an) would size its
+//! pool — just enough workers to keep a few ffmpeg children busy at once.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::{backend, find_audio_track, loudnorm, AppMsg};
+
+/// Upper bound on concurrent ffmpeg children, regardless of core count.
+const MAX_CONCURRENT_JOBS: usize = 4;
+
+/// Looks up a media file's duration (seconds) via ffprobe, for use as the
+/// denominator of ffmpeg `-progress` output. Returns `None` for inputs
+/// without a meaningful duration (e.g. a still image), in which case
+/// callers should fall back to an indeterminate spinner.
+pub fn probe_duration(file: &str, exe_dir: &PathBuf) -> Option<f64> {
+    let mut cmd = Command::new(backend::ffprobe_path(exe_dir));
+    let out = backend::hide_console(&mut cmd)
+        .args(&[
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+            file,
+        ])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    String::from_utf8(out.stdout)
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|d| *d > 0.0)
+}
+
+/// Runs `cmd` (expected to already include `-progress pipe:1 -nostats`) to
+/// completion, parsing ffmpeg's `-progress` key=value stream from stdout
+/// and reporting fractional completion through `on_progress`. If
+/// `total_duration` is `None` the child still runs to completion, just
+/// without progress callbacks, so the caller can show a spinner instead.
+pub fn run_with_progress(
+    mut cmd: Command,
+    total_duration: Option<f64>,
+    on_progress: impl Fn(f32),
+) -> std::io::Result<std::process::ExitStatus> {
+    cmd.stdout(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    // Always drain stdout to completion, even when `total_duration` is
+    // `None` — ffmpeg is actively writing `-progress` output into this
+    // pipe, and dropping the read end early (as we used to do by only
+    // taking `child.stdout` inside the `Some(total)` branch) gets ffmpeg
+    // killed by SIGPIPE instead of letting it finish. We just skip the
+    // `on_progress` callback when there's no duration to divide by.
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let Some(total) = total_duration else {
+                continue;
+            };
+            if let Some(us) = line.strip_prefix("out_time_us=") {
+                if let Ok(us) = us.parse::<f64>() {
+                    on_progress((us / 1_000_000.0 / total).clamp(0.0, 1.0));
+                }
+            } else if line == "progress=end" {
+                on_progress(1.0);
+            }
+        }
+    }
+    child.wait()
+}
+
+/// One row in the batch queue.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Job {
+    pub video: String,
+    pub audio: String,
+    pub output: String,
+    pub track: u32,
+    /// Loudness-normalize the dubbed track to a broadcast target instead
+    /// of stream-copying it as-is.
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+/// Per-job lifecycle, mirrored to the UI via `AppMsg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed(i32),
+}
+
+/// Number of worker threads to spin up for the dispatcher, sized from the
+/// machine's parallelism but capped since this workload is I/O-bound.
+fn pool_size() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(MAX_CONCURRENT_JOBS)
+}
+
+/// Spawn the dispatcher thread: pulls jobs from a shared queue and runs up
+/// to `pool_size()` of them at once, reporting status for each index back
+/// over `tx`.
+pub fn run_queue(jobs: Vec<Job>, exe_dir: PathBuf, tx: Sender<AppMsg>) {
+    thread::spawn(move || {
+        for idx in 0..jobs.len() {
+            let _ = tx.send(AppMsg::JobQueued(idx));
+        }
+        let queue: VecDeque<(usize, Job)> = jobs.into_iter().enumerate().collect();
+        let queue = Arc::new(Mutex::new(queue));
+
+        let workers = pool_size();
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let tx = tx.clone();
+                let exe_dir = exe_dir.clone();
+                thread::spawn(move || loop {
+                    let next = queue.lock().unwrap().pop_front();
+                    let (idx, job) = match next {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    let _ = tx.send(AppMsg::JobRunning(idx));
+                    match run_job(idx, &job, &exe_dir, &tx) {
+                        Ok(()) => {
+                            let _ = tx.send(AppMsg::JobDone(idx));
+                        }
+                        Err(code) => {
+                            let _ = tx.send(AppMsg::JobFailed(idx, code));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for h in handles {
+            let _ = h.join();
+        }
+        let _ = tx.send(AppMsg::QueueFinished);
+    });
+}
+
+/// Run a single merge job to completion, logging progress through `tx`.
+/// Returns `Err(exit_code)` on a non-zero ffmpeg exit (-1 if the process
+/// could not be spawned at all).
+fn run_job(idx: usize, job: &Job, exe_dir: &PathBuf, tx: &Sender<AppMsg>) -> Result<(), i32> {
+    let log = |m: String| {
+        let _ = tx.send(AppMsg::Log(m));
+    };
+    log(format!(
+        "[job {}] merging video='{}' audio='{}' track={} -> '{}'",
+        idx, job.video, job.audio, job.track, job.output
+    ));
+    let eng = find_audio_track(&job.video, "eng", exe_dir);
+    if let Some(stream) = eng {
+        log(format!("[job {}] found video 'eng' stream {}", idx, stream));
+    } else {
+        log(format!("[job {}] no 'eng' in video, using track 0", idx));
+    }
+    let mut cmd = Command::new(backend::ffmpeg_path(exe_dir));
+    backend::hide_console(&mut cmd).args(&[
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-y",
+        "-i",
+        &job.video,
+        "-i",
+        &job.audio,
+    ]);
+    if let Some(stream) = eng {
+        cmd.args(&["-map", &format!("0:{}", stream)]);
+    } else {
+        cmd.args(&["-map", "0:0", "-map", "0:1"]);
+    }
+    cmd.args(&["-map", &format!("1:{}", job.track)]);
+    if job.normalize {
+        log(format!(
+            "[job {}] measuring loudness of dubbed track...",
+            idx
+        ));
+        match loudnorm::measure(&job.audio, job.track, exe_dir) {
+            Ok(stats) => {
+                let filter = loudnorm::filter_for(&stats);
+                // The dub is always the last mapped audio stream (a:1);
+                // a bare `-c:a`/`-af` would also re-encode the video's
+                // own passthrough audio (a:0) through stats measured from
+                // the dub file. Target a:1 explicitly.
+                cmd.args(&[
+                    "-c:v",
+                    "copy",
+                    "-c:a:0",
+                    "copy",
+                    "-c:a:1",
+                    "aac",
+                    "-filter:a:1",
+                    filter.as_str(),
+                ]);
+            }
+            Err(e) => {
+                log(format!(
+                    "[job {}] loudnorm measurement failed ({}), falling back to stream copy",
+                    idx, e
+                ));
+                cmd.args(&["-c", "copy"]);
+            }
+        }
+    } else {
+        cmd.args(&["-c", "copy"]);
+    }
+    cmd.args(&["-progress", "pipe:1", "-nostats", &job.output]);
+    let total_duration = probe_duration(&job.video, exe_dir);
+    let tx_progress = tx.clone();
+    let result = run_with_progress(cmd, total_duration, |fraction| {
+        let _ = tx_progress.send(AppMsg::Progress(idx, fraction));
+    });
+    match result {
+        Ok(s) if s.success() => {
+            log(format!("[job {}] completed successfully", idx));
+            Ok(())
+        }
+        Ok(s) => {
+            let code = s.code().unwrap_or(-1);
+            log(format!("[job {}] ffmpeg exit code {:?}", idx, s.code()));
+            Err(code)
+        }
+        Err(e) => {
+            log(format!("[job {}] failed to run ffmpeg: {}", idx, e));
+            Err(-1)
+        }
+    }
+}
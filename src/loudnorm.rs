@@ -0,0 +1,68 @@
+//! EBU R128 two-pass loudness normalization for the external/dubbed audio
+//! track. Fan dubs are often far quieter or louder than the original
+//! audio; normalizing to a broadcast target (-16 LUFS) before muxing fixes
+//! that without re-encoding the video.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::backend;
+
+/// `I=-16:TP=-1.5:LRA=11`, the usual broadcast loudness target.
+pub const TARGET: &str = "I=-16:TP=-1.5:LRA=11";
+
+/// Stats measured by pass one, fed back into pass two as `measured_*` and
+/// `offset` so the filter doesn't have to re-scan the whole file.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LoudnormStats {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+/// Pass one: runs `loudnorm` in analysis mode (`-f null -`) and parses the
+/// JSON stats object ffmpeg prints on stderr.
+pub fn measure(audio: &str, track: u32, exe_dir: &PathBuf) -> Result<LoudnormStats, String> {
+    let mut cmd = Command::new(backend::ffmpeg_path(exe_dir));
+    let out = backend::hide_console(&mut cmd)
+        .args(&[
+            "-hide_banner",
+            "-i",
+            audio,
+            "-map",
+            &format!("0:{}", track),
+            "-af",
+            &format!("loudnorm={}:print_format=json", TARGET),
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .map_err(|e| format!("failed to run ffmpeg (loudnorm pass 1): {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let start = stderr
+        .rfind('{')
+        .ok_or_else(|| "loudnorm: no JSON stats found in ffmpeg stderr".to_string())?;
+    let end = stderr
+        .rfind('}')
+        .ok_or_else(|| "loudnorm: malformed JSON stats in ffmpeg stderr".to_string())?;
+    serde_json::from_str(&stderr[start..=end])
+        .map_err(|e| format!("failed to parse loudnorm stats: {}", e))
+}
+
+/// Pass two: the `-af` filter string, fed the pass-one measurements so
+/// ffmpeg can apply a single linear correction instead of re-analyzing.
+pub fn filter_for(stats: &LoudnormStats) -> String {
+    format!(
+        "loudnorm={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}",
+        TARGET,
+        stats.input_i,
+        stats.input_tp,
+        stats.input_lra,
+        stats.input_thresh,
+        stats.target_offset
+    )
+}
@@ -1,34 +1,69 @@
-#![windows_subsystem = "windows"]
+#![cfg_attr(windows, windows_subsystem = "windows")]
 //! Audio Merger GUI: bundle ffmpeg/ffprobe alongside exe, maximized, toggleable logs.
 
+mod backend;
+mod hls;
+mod jobs;
+mod loudnorm;
+mod project;
+
 use eframe::{egui, run_native, App, Frame, NativeOptions};
+use jobs::{Job, JobState};
 use log::info;
+use project::Project;
 use rfd::FileDialog;
 use serde_json::Value;
 use simplelog::{CombinedLogger, ConfigBuilder, WriteLogger};
 use std::fs::File;
-use std::os::windows::process::CommandExt;
 use std::path::PathBuf;
 use std::process::Command;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
 
 /// (stream_index, language_tag)
-type AudioStream = (u32, String);
+pub(crate) type AudioStream = (u32, String);
+
+/// Output format the merge produces.
+#[derive(PartialEq, Eq, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) enum OutputMode {
+    #[default]
+    Mkv,
+    Hls,
+}
+
+/// Messages sent from worker threads back to the UI thread.
+pub enum AppMsg {
+    Log(String),
+    JobQueued(usize),
+    JobRunning(usize),
+    JobDone(usize),
+    JobFailed(usize, i32),
+    Progress(usize, f32),
+    QueueFinished,
+    MergeDone,
+    MergeProgress(f32),
+}
 
 /// The main application state and UI logic.
 struct AudioMergerApp {
-    video_path: Option<String>,     // input video file
-    audio_path: Option<String>,     // external audio or dubbed video
-    output_path: Option<String>,    // output .mkv file
+    video_path: Option<String>,  // input video file
+    audio_path: Option<String>,  // external audio or dubbed video
+    output_path: Option<String>, // output .mkv file, or HLS package directory
+    output_mode: OutputMode,
+    normalize_audio: bool, // two-pass EBU R128 loudnorm on the dubbed track
     audio_tracks: Vec<AudioStream>, // extracted from audio_path
-    selected_track: Option<u32>,    // chosen audio stream index
+    selected_track: Option<u32>, // chosen audio stream index
     logs: Vec<String>,
-    rx: Receiver<String>,
-    tx: Sender<String>,
+    rx: Receiver<AppMsg>,
+    tx: Sender<AppMsg>,
     is_merging: bool,
     show_logs: bool,
-    exe_dir: PathBuf, // folder containing ffmpeg/ffprobe
+    exe_dir: PathBuf,           // exe's folder, checked first for bundled ffmpeg/ffprobe
+    queue: Vec<Job>,            // batch of jobs added via "Add to Queue"
+    queue_state: Vec<JobState>, // per-row state, indexed like `queue`
+    queue_progress: Vec<f32>,   // per-row fraction complete, indexed like `queue`
+    queue_running: bool,
+    merge_progress: Option<f32>, // fraction complete for the ad-hoc "Start Merge" run
 }
 
 impl Default for AudioMergerApp {
@@ -40,6 +75,8 @@ impl Default for AudioMergerApp {
             video_path: None,
             audio_path: None,
             output_path: None,
+            output_mode: OutputMode::Mkv,
+            normalize_audio: false,
             audio_tracks: Vec::new(),
             selected_track: None,
             logs: Vec::new(),
@@ -48,6 +85,11 @@ impl Default for AudioMergerApp {
             is_merging: false,
             show_logs: false,
             exe_dir: dir,
+            queue: Vec::new(),
+            queue_state: Vec::new(),
+            queue_progress: Vec::new(),
+            queue_running: false,
+            merge_progress: None,
         }
     }
 }
@@ -119,15 +161,17 @@ impl AudioMergerApp {
             return;
         }
         self.is_merging = true;
+        self.merge_progress = None;
         self.append_log(&format!(
             "Merging: video='{}', audio='{}', track={}, output='{}'",
             video, audio, track, output
         ));
+        let normalize = self.normalize_audio;
         let tx = self.tx.clone();
         let exe_dir = self.exe_dir.clone();
         thread::spawn(move || {
             let logger = |m: &str| {
-                let _ = tx.send(m.to_string());
+                let _ = tx.send(AppMsg::Log(m.to_string()));
             };
             // find English in video
             logger("ffprobe: searching for 'eng' in video...");
@@ -138,8 +182,8 @@ impl AudioMergerApp {
                 logger("No 'eng' in video, using track 0");
             }
             // build ffmpeg command
-            let mut cmd = Command::new(exe_dir.join("ffmpeg.exe"));
-            cmd.creation_flags(0x0800_0000).args(&[
+            let mut cmd = Command::new(backend::ffmpeg_path(&exe_dir));
+            backend::hide_console(&mut cmd).args(&[
                 "-hide_banner",
                 "-loglevel",
                 "error",
@@ -156,31 +200,318 @@ impl AudioMergerApp {
                 cmd.args(&["-map", "0:0", "-map", "0:1"]);
             }
             // external audio
-            cmd.args(&["-map", &format!("1:{}", track), "-c", "copy", &output]);
+            cmd.args(&["-map", &format!("1:{}", track)]);
+            if normalize {
+                logger("Measuring loudness of dubbed track...");
+                match loudnorm::measure(&audio, track, &exe_dir) {
+                    Ok(stats) => {
+                        let filter = loudnorm::filter_for(&stats);
+                        // The dub is always the *last* mapped audio stream
+                        // (a:1 — the video's own audio, if any, is mapped
+                        // first as a:0). A bare `-c:a`/`-af` applies to
+                        // every audio output stream, which would also
+                        // re-encode the passthrough original-language
+                        // track through loudnorm stats measured from the
+                        // dub file. Target a:1 explicitly and leave a:0
+                        // stream-copied.
+                        cmd.args(&[
+                            "-c:v",
+                            "copy",
+                            "-c:a:0",
+                            "copy",
+                            "-c:a:1",
+                            "aac",
+                            "-filter:a:1",
+                            filter.as_str(),
+                        ]);
+                    }
+                    Err(e) => {
+                        logger(&format!(
+                            "Loudnorm measurement failed ({}), falling back to stream copy",
+                            e
+                        ));
+                        cmd.args(&["-c", "copy"]);
+                    }
+                }
+            } else {
+                cmd.args(&["-c", "copy"]);
+            }
+            cmd.args(&["-progress", "pipe:1", "-nostats", &output]);
             logger("Running ffmpeg...");
-            match cmd.status() {
+            let total_duration = jobs::probe_duration(&video, &exe_dir);
+            if total_duration.is_none() {
+                logger("Could not determine duration, showing spinner instead of a progress bar");
+            }
+            let tx_progress = tx.clone();
+            let result = jobs::run_with_progress(cmd, total_duration, |fraction| {
+                let _ = tx_progress.send(AppMsg::MergeProgress(fraction));
+            });
+            match result {
                 Ok(s) if s.success() => logger("Merge completed successfully"),
                 Ok(s) => logger(&format!("ffmpeg exit code {:?}", s.code())),
                 Err(e) => logger(&format!("Failed to run ffmpeg: {}", e)),
             }
-            let _ = tx.send("MERGE_DONE".to_string());
+            let _ = tx.send(AppMsg::MergeDone);
+        });
+    }
+
+    /// Package the merge as an HLS VOD bundle instead of a single `.mkv`:
+    /// segment the video once, segment every detected audio rendition, and
+    /// write a master playlist tying them together.
+    fn start_hls_merge(&mut self) {
+        let video = match &self.video_path {
+            Some(v) => v.clone(),
+            None => {
+                self.append_log("Error: select video file");
+                return;
+            }
+        };
+        let audio = match &self.audio_path {
+            Some(a) => a.clone(),
+            None => {
+                self.append_log("Error: select audio file");
+                return;
+            }
+        };
+        let out_dir = match &self.output_path {
+            Some(o) => o.clone(),
+            None => {
+                self.append_log("Error: select output directory");
+                return;
+            }
+        };
+        if self.audio_tracks.is_empty() {
+            self.append_log("Error: no audio renditions detected in external file");
+            return;
+        }
+        if self.is_merging {
+            self.append_log("Merge already in progress");
+            return;
+        }
+        self.is_merging = true;
+        self.merge_progress = None;
+        self.append_log(&format!(
+            "Packaging HLS VOD: video='{}', audio='{}', out_dir='{}'",
+            video, audio, out_dir
+        ));
+        let tx = self.tx.clone();
+        let exe_dir = self.exe_dir.clone();
+        let tracks = self.audio_tracks.clone();
+        thread::spawn(move || {
+            let logger = |m: String| {
+                let _ = tx.send(AppMsg::Log(m));
+            };
+            let out_path = PathBuf::from(&out_dir);
+            if let Err(e) = std::fs::create_dir_all(&out_path) {
+                logger(format!("Failed to create output directory: {}", e));
+                let _ = tx.send(AppMsg::MergeDone);
+                return;
+            }
+            logger("Segmenting video...".to_string());
+            let video_playlist = match hls::segment_video(&video, &out_path, &exe_dir) {
+                Ok(p) => p,
+                Err(e) => {
+                    logger(format!("Video segmentation failed: {}", e));
+                    let _ = tx.send(AppMsg::MergeDone);
+                    return;
+                }
+            };
+            let mut renditions = Vec::new();
+            for stream in &tracks {
+                logger(format!("Segmenting audio rendition '{}'...", stream.1));
+                match hls::segment_audio(&audio, stream, &out_path, &exe_dir) {
+                    Ok(r) => renditions.push(r),
+                    Err(e) => logger(format!("Audio rendition '{}' failed: {}", stream.1, e)),
+                }
+            }
+            if renditions.is_empty() {
+                logger("No audio renditions succeeded, aborting".to_string());
+                let _ = tx.send(AppMsg::MergeDone);
+                return;
+            }
+            let master = match hls::build_master_playlist(&video_playlist, &renditions) {
+                Ok(m) => m,
+                Err(e) => {
+                    logger(format!("Failed to build master playlist: {}", e));
+                    let _ = tx.send(AppMsg::MergeDone);
+                    return;
+                }
+            };
+            let master_path = out_path.join("master.m3u8");
+            match std::fs::write(&master_path, master) {
+                Ok(()) => logger(format!("HLS package written to {}", master_path.display())),
+                Err(e) => logger(format!("Failed to write master playlist: {}", e)),
+            }
+            let _ = tx.send(AppMsg::MergeDone);
+        });
+    }
+
+    /// Append the currently selected video/audio/output/track as a new row
+    /// in the batch queue, so the user can build up many jobs before
+    /// running them together.
+    fn add_to_queue(&mut self) {
+        let (video, audio, output, track) = match (
+            &self.video_path,
+            &self.audio_path,
+            &self.output_path,
+            self.selected_track,
+        ) {
+            (Some(v), Some(a), Some(o), Some(t)) => (v.clone(), a.clone(), o.clone(), t),
+            _ => {
+                self.append_log("Error: select video, audio, stream and output before queuing");
+                return;
+            }
+        };
+        self.append_log(&format!("Queued: video='{}', output='{}'", video, output));
+        self.queue.push(Job {
+            video,
+            audio,
+            output,
+            track,
+            normalize: self.normalize_audio,
         });
+        self.queue_state.push(JobState::Queued);
+        self.queue_progress.push(0.0);
+    }
+
+    /// Run every job currently in the queue through the bounded worker pool.
+    fn run_queue(&mut self) {
+        if self.queue_running {
+            self.append_log("Queue already running");
+            return;
+        }
+        if self.queue.is_empty() {
+            self.append_log("Queue is empty");
+            return;
+        }
+        self.queue_running = true;
+        self.append_log(&format!("Starting queue of {} job(s)", self.queue.len()));
+        jobs::run_queue(self.queue.clone(), self.exe_dir.clone(), self.tx.clone());
+    }
+
+    /// Write the current session (single job fields + batch queue) to a
+    /// `.json` project file chosen via a save dialog.
+    fn save_project(&mut self) {
+        let project = Project {
+            video_path: self.video_path.clone(),
+            audio_path: self.audio_path.clone(),
+            selected_track: self.selected_track,
+            output_path: self.output_path.clone(),
+            output_mode: self.output_mode,
+            queue: self.queue.clone(),
+        };
+        let path = match FileDialog::new()
+            .add_filter("Project", &["json"])
+            .save_file()
+        {
+            Some(p) => p,
+            None => return,
+        };
+        match serde_json::to_string_pretty(&project) {
+            Ok(json) => match std::fs::write(&path, json) {
+                Ok(()) => self.append_log(&format!("Saved project to {}", path.display())),
+                Err(e) => self.append_log(&format!("Failed to write project file: {}", e)),
+            },
+            Err(e) => self.append_log(&format!("Failed to serialize project: {}", e)),
+        }
+    }
+
+    /// Load a `.json` project file, repopulating the single job fields and
+    /// batch queue, then re-probe the audio file to confirm the saved
+    /// stream index still exists.
+    fn load_project(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("Project", &["json"])
+            .pick_file()
+        {
+            Some(p) => p,
+            None => return,
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(t) => t,
+            Err(e) => {
+                self.append_log(&format!("Failed to read project file: {}", e));
+                return;
+            }
+        };
+        let project: Project = match serde_json::from_str(&text) {
+            Ok(p) => p,
+            Err(e) => {
+                self.append_log(&format!("Failed to parse project file: {}", e));
+                return;
+            }
+        };
+        self.video_path = project.video_path;
+        self.audio_path = project.audio_path;
+        self.output_path = project.output_path;
+        self.output_mode = project.output_mode;
+        self.queue = project.queue;
+        self.queue_state = vec![JobState::Queued; self.queue.len()];
+        self.queue_progress = vec![0.0; self.queue.len()];
+        self.append_log(&format!("Loaded project from {}", path.display()));
+        if self.audio_path.is_some() {
+            self.probe_audio_tracks();
+        }
+        if let Some(saved) = project.selected_track {
+            if self.audio_tracks.iter().any(|(i, _)| *i == saved) {
+                self.selected_track = Some(saved);
+            } else {
+                self.append_log(&format!(
+                    "Warning: saved audio stream index {} no longer exists — the file's stream layout may have changed",
+                    saved
+                ));
+            }
+        }
     }
 }
 
 impl App for AudioMergerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-        // process background logs
+        // process background logs and job/queue status
         while let Ok(msg) = self.rx.try_recv() {
-            if msg == "MERGE_DONE" {
-                self.is_merging = false;
-                self.append_log("Merge thread finished");
-            } else {
-                self.append_log(&msg);
+            match msg {
+                AppMsg::Log(m) => self.append_log(&m),
+                AppMsg::MergeDone => {
+                    self.is_merging = false;
+                    self.merge_progress = None;
+                    self.append_log("Merge thread finished");
+                }
+                AppMsg::MergeProgress(fraction) => {
+                    self.merge_progress = Some(fraction);
+                }
+                AppMsg::JobQueued(idx) => {
+                    self.queue_state[idx] = JobState::Queued;
+                }
+                AppMsg::JobRunning(idx) => {
+                    self.queue_state[idx] = JobState::Running;
+                    self.append_log(&format!("[job {}] started", idx));
+                }
+                AppMsg::JobDone(idx) => {
+                    self.queue_state[idx] = JobState::Done;
+                    self.queue_progress[idx] = 1.0;
+                }
+                AppMsg::JobFailed(idx, code) => {
+                    self.queue_state[idx] = JobState::Failed(code);
+                }
+                AppMsg::Progress(idx, fraction) => {
+                    self.queue_progress[idx] = fraction;
+                }
+                AppMsg::QueueFinished => {
+                    self.queue_running = false;
+                    self.append_log("Queue finished");
+                }
             }
         }
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Audio Merger GUI");
+            ui.horizontal(|ui| {
+                if ui.button("Save Project").clicked() {
+                    self.save_project();
+                }
+                if ui.button("Open Project").clicked() {
+                    self.load_project();
+                }
+            });
             ui.separator();
             // video selector
             ui.horizontal(|ui| {
@@ -239,13 +570,27 @@ impl App for AudioMergerApp {
                 });
                 ui.add_space(6.0);
             }
+            // output format selector
+            ui.horizontal(|ui| {
+                ui.label("Output format:");
+                ui.selectable_value(&mut self.output_mode, OutputMode::Mkv, "Single .mkv");
+                ui.selectable_value(&mut self.output_mode, OutputMode::Hls, "HLS VOD package");
+            });
+            ui.add_space(6.0);
             // output selector
             ui.horizontal(|ui| {
-                if ui.button("Select Output File").clicked() {
-                    if let Some(p) = FileDialog::new()
-                        .add_filter("Matroska MKV", &["mkv"])
-                        .save_file()
-                    {
+                let button_label = match self.output_mode {
+                    OutputMode::Mkv => "Select Output File",
+                    OutputMode::Hls => "Select Output Directory",
+                };
+                if ui.button(button_label).clicked() {
+                    let picked = match self.output_mode {
+                        OutputMode::Mkv => FileDialog::new()
+                            .add_filter("Matroska MKV", &["mkv"])
+                            .save_file(),
+                        OutputMode::Hls => FileDialog::new().pick_folder(),
+                    };
+                    if let Some(p) = picked {
                         if let Some(s) = p.to_str() {
                             self.output_path = Some(s.to_string());
                             self.append_log(&format!("Output: {}", s));
@@ -256,20 +601,83 @@ impl App for AudioMergerApp {
                     ui.label(o);
                 }
             });
+            ui.add_space(6.0);
+            ui.checkbox(
+                &mut self.normalize_audio,
+                "Normalize dubbed audio to -16 LUFS (two-pass loudnorm)",
+            );
             ui.add_space(8.0);
             // merge button
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!self.is_merging, egui::Button::new("Start Merge"))
+                    .clicked()
+                {
+                    match self.output_mode {
+                        OutputMode::Mkv => self.start_merge(),
+                        OutputMode::Hls => self.start_hls_merge(),
+                    }
+                }
+                if ui
+                    .add_enabled(
+                        self.output_mode == OutputMode::Mkv,
+                        egui::Button::new("Add to Queue"),
+                    )
+                    .clicked()
+                {
+                    self.add_to_queue();
+                }
+            });
+            // progress
+            if self.is_merging {
+                match self.merge_progress {
+                    Some(fraction) => {
+                        ui.add(
+                            egui::ProgressBar::new(fraction)
+                                .text(format!("{:.0}%", fraction * 100.0)),
+                        );
+                    }
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Merging in progress...");
+                        });
+                    }
+                }
+            }
+            ui.separator();
+            // batch queue
+            ui.heading("Batch Queue");
+            egui::Grid::new("queue_grid").striped(true).show(ui, |ui| {
+                ui.label("#");
+                ui.label("Video");
+                ui.label("Output");
+                ui.label("State");
+                ui.label("Progress");
+                ui.end_row();
+                for (i, job) in self.queue.iter().enumerate() {
+                    ui.label(format!("{}", i));
+                    ui.label(&job.video);
+                    ui.label(&job.output);
+                    let state_text = match self.queue_state[i] {
+                        JobState::Queued => "QUEUED".to_string(),
+                        JobState::Running => "RUNNING".to_string(),
+                        JobState::Done => "DONE".to_string(),
+                        JobState::Failed(code) => format!("FAILED ({})", code),
+                    };
+                    ui.label(state_text);
+                    let fraction = self.queue_progress[i];
+                    ui.add(
+                        egui::ProgressBar::new(fraction).text(format!("{:.0}%", fraction * 100.0)),
+                    );
+                    ui.end_row();
+                }
+            });
             if ui
-                .add_enabled(!self.is_merging, egui::Button::new("Start Merge"))
+                .add_enabled(!self.queue_running, egui::Button::new("Run Queue"))
                 .clicked()
             {
-                self.start_merge();
-            }
-            // progress
-            if self.is_merging {
-                ui.horizontal(|ui| {
-                    ui.spinner();
-                    ui.label("Merging in progress...");
-                });
+                self.run_queue();
             }
             ui.separator();
             // logs toggle
@@ -294,7 +702,7 @@ impl App for AudioMergerApp {
                     });
             }
         });
-        if self.is_merging {
+        if self.is_merging || self.queue_running {
             ctx.request_repaint();
         }
     }
@@ -302,9 +710,8 @@ impl App for AudioMergerApp {
 
 /// Bundled ffprobe extraction of audio streams
 fn get_all_audio_tracks(file: &str, exe_dir: &PathBuf) -> Vec<AudioStream> {
-    let ffprobe = exe_dir.join("ffprobe.exe");
-    let out = Command::new(ffprobe)
-        .creation_flags(0x0800_0000)
+    let mut cmd = Command::new(backend::ffprobe_path(exe_dir));
+    let out = backend::hide_console(&mut cmd)
         .args(&[
             "-hide_banner",
             "-loglevel",
@@ -345,7 +752,7 @@ fn get_all_audio_tracks(file: &str, exe_dir: &PathBuf) -> Vec<AudioStream> {
 }
 
 /// Find first stream matching language code
-fn find_audio_track(file: &str, code: &str, exe_dir: &PathBuf) -> Option<u32> {
+pub(crate) fn find_audio_track(file: &str, code: &str, exe_dir: &PathBuf) -> Option<u32> {
     get_all_audio_tracks(file, exe_dir)
         .into_iter()
         .find_map(|(i, l)| {
@@ -357,72 +764,10 @@ fn find_audio_track(file: &str, code: &str, exe_dir: &PathBuf) -> Option<u32> {
         })
 }
 
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStrExt;
-use std::ptr::null_mut;
-use winapi::um::handleapi::CloseHandle;
-use winapi::um::processthreadsapi::GetCurrentProcess;
-use winapi::um::processthreadsapi::OpenProcessToken;
-use winapi::um::securitybaseapi::GetTokenInformation;
-use winapi::um::shellapi::ShellExecuteW;
-use winapi::um::winnt::{TokenElevation, HANDLE, TOKEN_ELEVATION, TOKEN_QUERY};
-use winapi::um::winuser::SW_SHOW;
-
-/// Checks if current process has elevated privileges
-fn is_elevated() -> bool {
-    unsafe {
-        let mut token: HANDLE = null_mut();
-        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
-            return false;
-        }
-
-        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
-        let mut size = std::mem::size_of::<TOKEN_ELEVATION>() as u32;
-
-        let result = GetTokenInformation(
-            token,
-            TokenElevation,
-            &mut elevation as *mut _ as *mut _,
-            size,
-            &mut size,
-        );
-
-        CloseHandle(token);
-        result != 0 && elevation.TokenIsElevated != 0
-    }
-}
-
-/// Relaunches self with admin privileges via ShellExecuteW
-fn relaunch_as_admin() {
-    let exe = std::env::current_exe().unwrap();
-    let exe_w: Vec<u16> = OsStr::new(exe.to_str().unwrap())
-        .encode_wide()
-        .chain(std::iter::once(0))
-        .collect();
-
-    unsafe {
-        ShellExecuteW(
-            null_mut(),
-            widestring("runas").as_ptr(),
-            exe_w.as_ptr(),
-            null_mut(),
-            null_mut(),
-            SW_SHOW,
-        );
-    }
-    std::process::exit(0);
-}
-
-fn widestring(s: &str) -> Vec<u16> {
-    OsStr::new(s)
-        .encode_wide()
-        .chain(std::iter::once(0))
-        .collect()
-}
-
 fn main() {
-    if !is_elevated() {
-        relaunch_as_admin();
+    #[cfg(windows)]
+    if !backend::is_elevated() {
+        backend::relaunch_as_admin();
     }
 
     // logger to file
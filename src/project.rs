@@ -0,0 +1,27 @@
+//! Save/load a merge session as a small JSON config, so a repeatable
+//! dubbing workflow (same series, many episodes) doesn't need re-clicking
+//! every run.
+
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::Job;
+use crate::OutputMode;
+
+/// Definition of a merge session: the ad-hoc single job plus the batch
+/// queue, serialized so the same run can be reproduced later. Queued jobs
+/// keep their own `normalize` flag; the ad-hoc job's loudness-normalize
+/// checkbox is not saved and is left as it is on load.
+#[derive(Serialize, Deserialize)]
+pub struct Project {
+    pub video_path: Option<String>,
+    pub audio_path: Option<String>,
+    pub selected_track: Option<u32>,
+    pub output_path: Option<String>,
+    /// Defaults to `Mkv` on load for project files saved before this
+    /// field existed, so an old project still opens (into the mode it
+    /// was actually created in) rather than failing to parse.
+    #[serde(default)]
+    pub output_mode: OutputMode,
+    #[serde(default)]
+    pub queue: Vec<Job>,
+}